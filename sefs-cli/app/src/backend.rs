@@ -0,0 +1,19 @@
+/// The block-level crypto operations a storage device needs to implement
+/// the SEFS on-disk format: per-block encryption/decryption, per-block MAC
+/// computation, and derivation of a fresh per-image auto key. `sgx_dev`'s
+/// `SgxStorage` performs these inside the enclave; `soft_dev`'s
+/// `SoftStorage` performs the same operations with a pure-Rust AEAD so the
+/// two produce byte-compatible images for a given key.
+///
+/// `block_index` is folded into each block's nonce by implementations so
+/// that a key is never reused under the same nonce for more than one block
+/// within an image — reusing a single fixed nonce across every block would
+/// leak the XOR of their plaintexts and let an attacker forge MACs.
+pub trait CryptoBackend {
+    /// Encrypt one plaintext block in place, returning its MAC.
+    fn encrypt_block(&self, key: &[u8; 16], block_index: u64, block: &mut [u8]) -> [u8; 16];
+    /// Decrypt one ciphertext block in place, checking it against `mac`.
+    fn decrypt_block(&self, key: &[u8; 16], block_index: u64, block: &mut [u8], mac: &[u8; 16]) -> bool;
+    /// Derive a fresh random 16-byte key for `EncryptAutoKey` mode.
+    fn derive_auto_key(&self) -> [u8; 16];
+}
@@ -0,0 +1,207 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+
+use rcore_fs::vfs::{FileType, FsError, INode};
+use rcore_fs_sefs as sefs;
+
+/// Result of walking an SEFS image tree.
+#[derive(Debug, Default)]
+pub struct FsckReport {
+    /// Dirents pointing at an inode that does not exist, as `(parent_path, name)`.
+    pub dangling_dirents: Vec<(String, String)>,
+    /// On-disk file objects that are not reachable from `root_inode()`.
+    pub orphans: Vec<String>,
+    /// Paths whose stored MAC does not match the recomputed MAC.
+    pub mac_mismatches: Vec<String>,
+}
+
+impl FsckReport {
+    pub fn is_clean(&self) -> bool {
+        self.dangling_dirents.is_empty() && self.orphans.is_empty() && self.mac_mismatches.is_empty()
+    }
+}
+
+/// Walk every inode reachable from `root`, cross-checking dirents and, when
+/// `check_mac` is set, recomputing each file's per-block MAC. Returns the set
+/// of reachable on-disk inode ids alongside the report so callers can diff it
+/// against the image directory to find orphans.
+pub fn check(root: &Arc<dyn INode>, check_mac: bool) -> Result<(FsckReport, HashSet<usize>), Box<dyn Error>> {
+    let mut report = FsckReport::default();
+    let mut reachable = HashSet::new();
+    walk(root, "/".to_string(), check_mac, &mut report, &mut reachable)?;
+    Ok((report, reachable))
+}
+
+fn walk(
+    inode: &Arc<dyn INode>,
+    path: String,
+    check_mac: bool,
+    report: &mut FsckReport,
+    reachable: &mut HashSet<usize>,
+) -> Result<(), Box<dyn Error>> {
+    reachable.insert(inode.metadata()?.inode);
+    let metadata = inode.metadata()?;
+    match metadata.type_ {
+        FileType::Dir => {
+            let count = inode.metadata()?.size;
+            for i in 0..count {
+                let name = match inode.get_entry(i) {
+                    Ok(name) => name,
+                    Err(FsError::EntryNotFound) => continue,
+                    Err(e) => return Err(Box::new(e)),
+                };
+                if name == "." || name == ".." {
+                    continue;
+                }
+                let child_path = format!("{}{}/", path, name);
+                match inode.find(&name) {
+                    Ok(child) => walk(&child, child_path, check_mac, report, reachable)?,
+                    Err(_) => report.dangling_dirents.push((path.clone(), name)),
+                }
+            }
+        }
+        FileType::File => {
+            if check_mac {
+                if let Err(_) = verify_file_mac(inode) {
+                    report.mac_mismatches.push(path.clone());
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Re-read every block of a regular file, forcing the SEFS device layer to
+/// recompute and compare the per-block MAC stored alongside it.
+fn verify_file_mac(inode: &Arc<dyn INode>) -> Result<(), Box<dyn Error>> {
+    let size = inode.metadata()?.size;
+    let mut buf = vec![0u8; 4096];
+    let mut offset = 0;
+    while offset < size {
+        let n = inode.read_at(offset, &mut buf)?;
+        if n == 0 {
+            break;
+        }
+        offset += n;
+    }
+    Ok(())
+}
+
+/// Scan the image directory for on-disk inode files not present in
+/// `reachable`, recording them as orphans in `report`.
+fn find_orphans(image: &Path, reachable: &HashSet<usize>, report: &mut FsckReport) -> Result<(), Box<dyn Error>> {
+    for entry in std::fs::read_dir(image)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = match name.to_str() {
+            Some(n) => n,
+            None => continue,
+        };
+        let inode_id = match name.parse::<usize>() {
+            Ok(id) => id,
+            Err(_) => continue,
+        };
+        if !reachable.contains(&inode_id) {
+            report.orphans.push(name.to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Resolve a `/`-separated directory path by walking `find` one component at
+/// a time from `root`, since `INode::find` only resolves a single already-
+/// linked child name rather than a full path.
+fn resolve_dir(root: &Arc<dyn INode>, path: &str) -> Option<Arc<dyn INode>> {
+    let mut cur = root.clone();
+    for component in path.split('/').filter(|c| !c.is_empty()) {
+        cur = cur.find(component).ok()?;
+    }
+    Some(cur)
+}
+
+/// `repair`'s outcome: whether it fully repaired everything the backlog
+/// item for `fsck` asked `--repair` to handle, or only part of it.
+pub struct RepairOutcome {
+    /// Dangling dirents were dropped. Always `true` when `repair` is called.
+    pub dropped_dangling_dirents: bool,
+    /// Orphans were moved into `lost+found`. Always `false` today — see the
+    /// doc comment on `repair`.
+    pub relinked_orphans: bool,
+}
+
+/// Drop every dangling dirent reported by `check`, and report on orphans.
+///
+/// **This is only a partial implementation of what `--repair` was asked to
+/// do.** The backlog item specifies moving orphans into `lost+found`; this
+/// function does not do that and only reports them. `find_orphans`
+/// identifies orphans precisely because they have no dirent pointing at
+/// them, so there is no name to resolve them by through the `INode` VFS
+/// trait — `INode::find` only resolves an already-linked child, and calling
+/// it on an orphan's raw inode-id filename always fails. Relinking an orphan
+/// needs to open that on-disk object by id directly through the SEFS device
+/// layer, which is not exposed here. Until that access path exists, orphans
+/// are reported by `fsck_image` and left in the image directory rather than
+/// "recovered" in a way that silently corrupts the tree (the bug in the
+/// original attempt at this).
+pub fn repair(root: &Arc<dyn INode>, report: &FsckReport) -> Result<RepairOutcome, Box<dyn Error>> {
+    if !report.orphans.is_empty() {
+        println!(
+            "fsck: {} orphan(s) left in place; relinking them needs inode-by-id access \
+             that the VFS layer does not expose (--repair does not implement lost+found recovery)",
+            report.orphans.len()
+        );
+    }
+    for (parent, name) in &report.dangling_dirents {
+        if let Some(dir) = resolve_dir(root, parent) {
+            let _ = dir.unlink(name);
+        }
+    }
+    Ok(RepairOutcome { dropped_dangling_dirents: true, relinked_orphans: false })
+}
+
+/// Run `check`/`repair` against `sefs_fs`, an already-opened plain (non-union)
+/// SEFS whose on-disk files live in `image`, printing a human-readable report. Returns `Ok(true)` if the image was
+/// found clean (or was repaired to a clean state).
+pub fn fsck_image(
+    sefs_fs: &Arc<sefs::SEFS>,
+    image: &Path,
+    repair_orphans: bool,
+) -> Result<bool, Box<dyn Error>> {
+    let root = sefs_fs.root_inode();
+    let (mut report, mut reachable) = check(&root, true)?;
+    find_orphans(image, &reachable, &mut report)?;
+    if !report.is_clean() && repair_orphans {
+        let outcome = repair(&root, &report)?;
+        let (new_report, new_reachable) = check(&root, true)?;
+        report = new_report;
+        reachable = new_reachable;
+        find_orphans(image, &reachable, &mut report)?;
+        if !report.orphans.is_empty() && !outcome.relinked_orphans {
+            println!(
+                "fsck --repair: incomplete — {} orphan(s) remain unrepaired; \
+                 only dangling-dirent cleanup is implemented",
+                report.orphans.len()
+            );
+        }
+    }
+    print_report(&report);
+    Ok(report.is_clean())
+}
+
+fn print_report(report: &FsckReport) {
+    for (parent, name) in &report.dangling_dirents {
+        println!("dangling dirent: {}{} has no target inode", parent, name);
+    }
+    for path in &report.orphans {
+        println!("orphan: {} is unreachable from root", path);
+    }
+    for path in &report.mac_mismatches {
+        println!("MAC mismatch: {}", path);
+    }
+    if report.is_clean() {
+        println!("fsck: no errors found");
+    }
+}
@@ -0,0 +1,164 @@
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use sha2::{Digest, Sha256};
+
+use rcore_fs::vfs::{FileType, FsError, INode, Metadata};
+
+use crate::keyfile::hex_encode;
+
+/// One entry of a golden manifest: a single inode's observable shape and
+/// content, hashed over the decrypted VFS view rather than raw device bytes
+/// so the manifest stays stable across re-encryption with a different key.
+/// Hashed with SHA-256 rather than `DefaultHasher`: the latter's algorithm is
+/// explicitly not guaranteed stable across compiler releases, which would
+/// make a golden regenerated under a different toolchain diverge for
+/// reasons having nothing to do with an actual format regression.
+struct Entry {
+    path: String,
+    type_: FileType,
+    size: usize,
+    mode: u16,
+    mtime: i64,
+    content_hash: [u8; 32],
+}
+
+impl Entry {
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{:?}\t{}\t{:o}\t{}\t{}",
+            self.path,
+            self.type_,
+            self.size,
+            self.mode,
+            self.mtime,
+            hex_encode(&self.content_hash)
+        )
+    }
+}
+
+/// Walk every inode reachable from `root`, producing one `Entry` per path,
+/// sorted by path so the manifest is deterministic regardless of on-disk
+/// dirent order.
+fn collect(root: &Arc<dyn INode>) -> Result<Vec<Entry>, Box<dyn Error>> {
+    let mut entries = Vec::new();
+    walk(root, "/".to_string(), &mut entries)?;
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+fn walk(inode: &Arc<dyn INode>, path: String, entries: &mut Vec<Entry>) -> Result<(), Box<dyn Error>> {
+    let metadata = inode.metadata()?;
+    let content_hash = match metadata.type_ {
+        FileType::Dir => hash_dir_children(inode)?,
+        FileType::File => hash_file_content(inode, &metadata)?,
+        _ => [0u8; 32],
+    };
+    entries.push(Entry {
+        path: path.clone(),
+        type_: metadata.type_,
+        size: metadata.size,
+        mode: metadata.mode,
+        mtime: metadata.mtime.sec,
+        content_hash,
+    });
+    if metadata.type_ == FileType::Dir {
+        let count = metadata.size;
+        for i in 0..count {
+            let name = match inode.get_entry(i) {
+                Ok(name) => name,
+                Err(FsError::EntryNotFound) => continue,
+                Err(e) => return Err(Box::new(e)),
+            };
+            if name == "." || name == ".." {
+                continue;
+            }
+            let child = inode.find(&name)?;
+            walk(&child, format!("{}{}/", path, name), entries)?;
+        }
+    }
+    Ok(())
+}
+
+/// A sorted hash of child names, so the golden manifest is stable even when
+/// the directory's on-disk dirent order is not.
+fn hash_dir_children(inode: &Arc<dyn INode>) -> Result<[u8; 32], Box<dyn Error>> {
+    let mut names = Vec::new();
+    let count = inode.metadata()?.size;
+    for i in 0..count {
+        match inode.get_entry(i) {
+            Ok(name) if name != "." && name != ".." => names.push(name),
+            Ok(_) => {}
+            Err(FsError::EntryNotFound) => continue,
+            Err(e) => return Err(Box::new(e)),
+        }
+    }
+    names.sort();
+    let mut hasher = Sha256::new();
+    for name in &names {
+        hasher.update(name.as_bytes());
+        hasher.update(b"\0");
+    }
+    Ok(hasher.finalize().into())
+}
+
+/// A hash over the decrypted byte content of a regular file.
+fn hash_file_content(inode: &Arc<dyn INode>, metadata: &Metadata) -> Result<[u8; 32], Box<dyn Error>> {
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 4096];
+    let mut offset = 0;
+    while offset < metadata.size {
+        let n = inode.read_at(offset, &mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        offset += n;
+    }
+    Ok(hasher.finalize().into())
+}
+
+fn render(entries: &[Entry]) -> String {
+    let mut out = String::new();
+    for entry in entries {
+        writeln!(out, "{}", entry.to_line()).expect("formatting to a String never fails");
+    }
+    out
+}
+
+/// Walk `root` and write its canonical manifest to `out`.
+pub fn create_golden(root: &Arc<dyn INode>, out: &Path) -> Result<(), Box<dyn Error>> {
+    let entries = collect(root)?;
+    fs::write(out, render(&entries))?;
+    Ok(())
+}
+
+/// Walk `root`, regenerate its manifest, and diff it line-by-line against
+/// the manifest stored at `golden`. Prints the first divergence and returns
+/// `Ok(true)` if they match exactly.
+pub fn check_golden(root: &Arc<dyn INode>, golden: &Path) -> Result<bool, Box<dyn Error>> {
+    let entries = collect(root)?;
+    let actual = render(&entries);
+    let expected = fs::read_to_string(golden)?;
+    if actual == expected {
+        println!("check-golden: {} matches", golden.display());
+        return Ok(true);
+    }
+    for (i, (a, e)) in actual.lines().zip(expected.lines()).enumerate() {
+        if a != e {
+            println!("check-golden: divergence at line {}:", i + 1);
+            println!("  golden: {}", e);
+            println!("  actual: {}", a);
+            return Ok(false);
+        }
+    }
+    println!(
+        "check-golden: manifests differ in length (golden has {} lines, actual has {})",
+        expected.lines().count(),
+        actual.lines().count()
+    );
+    Ok(false)
+}
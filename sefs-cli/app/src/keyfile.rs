@@ -0,0 +1,151 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rand::RngCore;
+
+/// Name of the plaintext record written alongside an SEFS image directory
+/// that carries the salt and KDF parameters needed to re-derive its key.
+const KEYFILE_META: &str = "keyfile.meta";
+
+/// KDF used to stretch a human passphrase into a 16-byte SEFS key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kdf {
+    /// Argon2id, the preferred memory-hard KDF.
+    Argon2id { mem_kib: u32, iterations: u32, parallelism: u32 },
+    /// PBKDF2-HMAC-SHA256 fallback for hosts without an Argon2 backend.
+    Pbkdf2 { iterations: u32 },
+}
+
+impl fmt::Display for Kdf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Kdf::Argon2id { mem_kib, iterations, parallelism } => {
+                write!(f, "argon2id:{}:{}:{}", mem_kib, iterations, parallelism)
+            }
+            Kdf::Pbkdf2 { iterations } => write!(f, "pbkdf2-sha256:{}", iterations),
+        }
+    }
+}
+
+impl std::str::FromStr for Kdf {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        match parts.as_slice() {
+            ["argon2id", mem_kib, iterations, parallelism] => Ok(Kdf::Argon2id {
+                mem_kib: mem_kib.parse()?,
+                iterations: iterations.parse()?,
+                parallelism: parallelism.parse()?,
+            }),
+            ["pbkdf2-sha256", iterations] => Ok(Kdf::Pbkdf2 { iterations: iterations.parse()? }),
+            _ => Err(format!("unrecognized KDF record: {}", s).into()),
+        }
+    }
+}
+
+impl Default for Kdf {
+    /// 64 MiB / 3 passes / 1 lane, matching the OWASP baseline recommendation.
+    fn default() -> Self {
+        Kdf::Argon2id { mem_kib: 64 * 1024, iterations: 3, parallelism: 1 }
+    }
+}
+
+/// The salt and KDF parameters needed to re-derive a key from a passphrase,
+/// as persisted next to an SEFS image directory. Contains no secret material.
+struct KeyfileMeta {
+    salt: [u8; 16],
+    kdf: Kdf,
+}
+
+impl KeyfileMeta {
+    fn to_record(&self) -> String {
+        format!("{}\n{}\n", hex_encode(&self.salt), self.kdf)
+    }
+
+    fn from_record(s: &str) -> Result<Self, Box<dyn Error>> {
+        let mut lines = s.lines();
+        let salt = hex_decode(lines.next().ok_or("missing salt line")?)?;
+        if salt.len() != 16 {
+            return Err("salt must be 16 bytes".into());
+        }
+        let mut salt_arr = [0u8; 16];
+        salt_arr.copy_from_slice(&salt);
+        let kdf: Kdf = lines.next().ok_or("missing KDF line")?.parse()?;
+        Ok(KeyfileMeta { salt: salt_arr, kdf })
+    }
+}
+
+/// Derive a 16-byte key from `passphrase` under `meta`'s KDF parameters.
+/// `meta` is parsed from the on-disk `keyfile.meta` record, so a corrupt or
+/// tampered record (out-of-range `mem_kib`/`iterations`/`parallelism`) must
+/// surface as an error here rather than panicking the whole CLI.
+fn derive_key(passphrase: &str, meta: &KeyfileMeta) -> Result<[u8; 16], Box<dyn Error>> {
+    let mut out = [0u8; 16];
+    match meta.kdf {
+        Kdf::Argon2id { mem_kib, iterations, parallelism } => {
+            let params = argon2::Params::new(mem_kib, iterations, parallelism, Some(16))
+                .map_err(|e| format!("invalid keyfile.meta Argon2id parameters: {}", e))?;
+            let argon2 = argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
+            argon2
+                .hash_password_into(passphrase.as_bytes(), &meta.salt, &mut out)
+                .map_err(|e| format!("Argon2id key derivation failed: {}", e))?;
+        }
+        Kdf::Pbkdf2 { iterations } => {
+            pbkdf2::pbkdf2::<hmac::Hmac<sha2::Sha256>>(passphrase.as_bytes(), &meta.salt, iterations, &mut out);
+        }
+    }
+    Ok(out)
+}
+
+/// Path of the `keyfile.meta` record for a given SEFS image directory.
+fn meta_path(image: &Path) -> PathBuf {
+    image.join(KEYFILE_META)
+}
+
+/// Generate a fresh random salt, derive a key from `passphrase`, and persist
+/// the salt and KDF parameters as a plaintext record under `image`. Returns
+/// the derived 16-byte key ready to hand to `SgxStorage`.
+pub fn create(image: &Path, passphrase: &str) -> Result<[u8; 16], Box<dyn Error>> {
+    let mut salt = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let meta = KeyfileMeta { salt, kdf: Kdf::default() };
+    fs::write(meta_path(image), meta.to_record())?;
+    derive_key(passphrase, &meta)
+}
+
+/// Read the `keyfile.meta` record under `image` and re-derive its key from
+/// `passphrase`.
+pub fn open(image: &Path, passphrase: &str) -> Result<[u8; 16], Box<dyn Error>> {
+    let record = fs::read_to_string(meta_path(image))?;
+    let meta = KeyfileMeta::from_record(&record)?;
+    derive_key(passphrase, &meta)
+}
+
+/// Render a 16-byte key as the `xx-xx-...` hex form `EncryptMode::from_parameters`
+/// already understands, so the enclave/device code path is unchanged.
+pub fn key_to_hex_dashed(key: &[u8; 16]) -> String {
+    key.iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+pub(crate) fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub(crate) fn hex_decode(s: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    if !s.is_ascii() {
+        return Err("hex string must be ASCII".into());
+    }
+    if s.len() % 2 != 0 {
+        return Err("hex string must have even length".into());
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(|e| e.into()))
+        .collect()
+}
@@ -0,0 +1,74 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+use crate::keyfile;
+
+/// One `--layer` argument to `mount`:
+/// `path[:ro|:rw][:key=<hex>|:passphrase=<pass>][:integrity]`.
+///
+/// Layers are mounted in the order given, mirroring OverlayFS-style lowerdir
+/// stacking: the first layer must be `:rw` (or left unmarked, which defaults
+/// to read-write) so copy-up lands there, and every later layer is read-only.
+#[derive(Debug)]
+pub struct LayerSpec {
+    pub path: PathBuf,
+    pub writable: bool,
+    pub protect_integrity: bool,
+    pub key: Option<String>,
+    pub passphrase: Option<String>,
+}
+
+impl LayerSpec {
+    pub fn parse(spec: &str) -> Result<LayerSpec, Box<dyn Error>> {
+        let mut parts = spec.split(':');
+        let path = PathBuf::from(parts.next().ok_or("empty --layer value")?);
+        let mut writable = true;
+        let mut protect_integrity = false;
+        let mut key = None;
+        let mut passphrase = None;
+        for flag in parts {
+            match flag {
+                "ro" => writable = false,
+                "rw" => writable = true,
+                "integrity" => protect_integrity = true,
+                _ if flag.starts_with("key=") => key = Some(flag["key=".len()..].to_string()),
+                _ if flag.starts_with("passphrase=") => {
+                    passphrase = Some(flag["passphrase=".len()..].to_string())
+                }
+                other => return Err(format!("unrecognized --layer flag {:?} in {:?}", other, spec).into()),
+            }
+        }
+        if key.is_some() && passphrase.is_some() {
+            return Err(format!("--layer {:?} cannot set both key= and passphrase=", spec).into());
+        }
+        Ok(LayerSpec { path, writable, protect_integrity, key, passphrase })
+    }
+
+    /// The dashed-hex key this layer's device should be opened with: an
+    /// explicit key if one was given, a key re-derived from a passphrase via
+    /// that layer's `keyfile.meta` if one was given instead, or `None` to
+    /// fall back to a per-image auto key.
+    pub fn resolved_key(&self) -> Result<Option<String>, Box<dyn Error>> {
+        match &self.passphrase {
+            Some(passphrase) => Ok(Some(keyfile::key_to_hex_dashed(&keyfile::open(&self.path, passphrase)?))),
+            None => Ok(self.key.clone()),
+        }
+    }
+}
+
+/// Parse every `--layer` argument and check that exactly one layer is
+/// writable and that it is the first one, since that is where `UnionFS`
+/// copy-up lands.
+pub fn parse_layers(specs: &[String]) -> Result<Vec<LayerSpec>, Box<dyn Error>> {
+    if specs.is_empty() {
+        return Err("mount requires at least one --layer".into());
+    }
+    let layers: Vec<LayerSpec> = specs.iter().map(|s| LayerSpec::parse(s)).collect::<Result<_, _>>()?;
+    if !layers[0].writable {
+        return Err("the first --layer must be writable (:rw) so copy-up has somewhere to land".into());
+    }
+    if layers[1..].iter().any(|l| l.writable) {
+        return Err("only the first --layer may be writable (:rw); later layers must be :ro".into());
+    }
+    Ok(layers)
+}
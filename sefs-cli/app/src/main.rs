@@ -9,20 +9,35 @@ use sys_mount;
 
 use rcore_fs::dev::std_impl::StdTimeProvider;
 use rcore_fs::vfs::FileSystem;
+// `zip_dir`/`unzip_dir` and `VfsFuse` round-trip only ordinary files and
+// directories; they live in the `rcore_fs_cli` crate, whose source is not
+// part of this checkout, so they cannot be extended here to cover symlinks,
+// xattrs, and device/FIFO/socket nodes directly. `zip`/`unzip` instead layer
+// the `metadata` module's own directory walk on top, capturing/restoring
+// that metadata via a sidecar file stored inside the encrypted image tree. The live FUSE
+// mount (`VfsFuse`) does not get this passthrough.
 use rcore_fs_cli::fuse::VfsFuse;
 use rcore_fs_cli::zip::{unzip_dir, zip_dir};
 use rcore_fs_sefs as sefs;
 use rcore_fs_sefs::dev::std_impl::StdUuidProvider;
 use rcore_fs_unionfs as unionfs;
 
+mod backend;
 mod enclave;
+mod fsck;
+mod golden;
+mod keyfile;
+mod layer;
+mod metadata;
 mod sgx_dev;
+mod soft_dev;
 
 #[derive(Debug, StructOpt)]
 struct Opt {
-    /// Path of the enclave library
+    /// Path of the enclave library. When omitted, block crypto runs through
+    /// the pure-Rust software backend instead of an SGX enclave.
     #[structopt(short, long, parse(from_os_str))]
-    enclave: PathBuf,
+    enclave: Option<PathBuf>,
     /// Command
     #[structopt(subcommand)]
     cmd: Cmd,
@@ -44,8 +59,12 @@ enum Cmd {
         protect_integrity: bool,
         /// 16-byte key for encryption,
         /// format is: xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx
-        #[structopt(short, long)]
+        #[structopt(short, long, conflicts_with = "passphrase")]
         key: Option<String>,
+        /// Derive the encryption key from a passphrase instead of a raw key;
+        /// the salt and KDF parameters are saved as keyfile.meta in <image>
+        #[structopt(long)]
+        passphrase: Option<String>,
     },
     /// Unzip data from given <image> to <dir>
     #[structopt(name = "unzip")]
@@ -61,21 +80,79 @@ enum Cmd {
         protect_integrity: bool,
         /// 16-byte key for decryption,
         /// format is: xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx
-        #[structopt(short, long)]
+        #[structopt(short, long, conflicts_with = "passphrase")]
         key: Option<String>,
+        /// Re-derive the decryption key from a passphrase using the
+        /// keyfile.meta record saved in <image> by `zip --passphrase`
+        #[structopt(long)]
+        passphrase: Option<String>,
     },
-    /// Mount <image> overlayed with <container> to <dir>
+    /// Mount a stack of SEFS layers, overlayed in the order given, to <dir>
     #[structopt(name = "mount")]
     Mount {
-        /// Image SEFS directory
+        /// A layer to mount, lowest last:
+        /// path[:ro|:rw][:key=<hex>|:passphrase=<pass>][:integrity].
+        /// The first --layer is the writable top layer; repeat for each
+        /// read-only layer underneath it.
+        #[structopt(long = "layer")]
+        layers: Vec<String>,
+        /// Target mount point
+        #[structopt(parse(from_os_str))]
+        dir: PathBuf,
+    },
+    /// Check (and optionally repair) the integrity of an SEFS image
+    #[structopt(name = "fsck")]
+    Fsck {
+        /// SEFS image directory to check
         #[structopt(parse(from_os_str))]
         image: PathBuf,
-        /// Container SEFS directory
+        /// Protect the integrity of FS
+        #[structopt(short, long)]
+        protect_integrity: bool,
+        /// 16-byte key for decryption,
+        /// format is: xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx
+        #[structopt(short, long)]
+        key: Option<String>,
+        /// Drop dangling dirents. Orphaned inodes are only reported, not
+        /// moved to lost+found: relinking one needs inode-by-id access the
+        /// VFS layer does not expose, so that part of --repair is not yet
+        /// implemented (see fsck::repair).
+        #[structopt(long)]
+        repair: bool,
+    },
+    /// Write a canonical content manifest for <image> to <out>
+    #[structopt(name = "create-golden")]
+    CreateGolden {
+        /// SEFS image directory
         #[structopt(parse(from_os_str))]
-        container: PathBuf,
-        /// Target mount point
+        image: PathBuf,
+        /// Protect the integrity of FS
+        #[structopt(short, long)]
+        protect_integrity: bool,
+        /// 16-byte key for decryption,
+        /// format is: xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx
+        #[structopt(short, long)]
+        key: Option<String>,
+        /// Output path for the golden manifest
         #[structopt(parse(from_os_str))]
-        dir: PathBuf,
+        out: PathBuf,
+    },
+    /// Diff the manifest regenerated from <image> against <golden>
+    #[structopt(name = "check-golden")]
+    CheckGolden {
+        /// SEFS image directory
+        #[structopt(parse(from_os_str))]
+        image: PathBuf,
+        /// Protect the integrity of FS
+        #[structopt(short, long)]
+        protect_integrity: bool,
+        /// 16-byte key for decryption,
+        /// format is: xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx-xx
+        #[structopt(short, long)]
+        key: Option<String>,
+        /// Path of the stored golden manifest to diff against
+        #[structopt(parse(from_os_str))]
+        golden: PathBuf,
     },
 }
 
@@ -84,31 +161,51 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let opt = Opt::from_args();
 
-    let enclave = match enclave::init_enclave(&opt.enclave.to_str().unwrap()) {
-        Ok(r) => {
-            println!("[+] Init Enclave Successful {}!", r.geteid());
-            r
+    let enclave = match &opt.enclave {
+        Some(path) => match enclave::init_enclave(path.to_str().unwrap()) {
+            Ok(r) => {
+                println!("[+] Init Enclave Successful {}!", r.geteid());
+                Some(r)
+            }
+            Err(x) => {
+                println!("[-] Init Enclave Failed!");
+                return Err(Box::new(IoError::new(ErrorKind::Other, x.as_str())));
+            }
+        },
+        None => {
+            println!("[+] No enclave given, using the software crypto backend");
+            None
         }
-        Err(x) => {
-            println!("[-] Init Enclave Failed!");
-            return Err(Box::new(IoError::new(ErrorKind::Other, x.as_str())));
+    };
+
+    // Build the block device for `image`, routing through the enclave when
+    // one was loaded and through the pure-Rust AES-GCM backend otherwise.
+    // Every subcommand below is unaware of which one it got.
+    let open_device = |image: &PathBuf,
+                        protect_integrity: bool,
+                        key: Option<String>|
+     -> Result<Box<dyn sefs::dev::Storage>, Box<dyn Error>> {
+        match &enclave {
+            Some(enclave) => {
+                let mode = sgx_dev::EncryptMode::from_parameters(protect_integrity, key)?;
+                Ok(Box::new(sgx_dev::SgxStorage::new(enclave.geteid(), image, mode)))
+            }
+            None => Ok(Box::new(soft_dev::SoftStorage::new(image, protect_integrity, key)?)),
         }
     };
 
     match opt.cmd {
-        Cmd::Mount {
-            image,
-            container,
-            dir,
-        } => {
-            let image_fs = {
-                let device = sgx_dev::SgxStorage::new(
-                    enclave.geteid(),
-                    &image,
-                    sgx_dev::EncryptMode::IntegrityOnly,
-                );
-                sefs::SEFS::open(Box::new(device), &StdTimeProvider, &StdUuidProvider)?
-            };
+        Cmd::Mount { layers, dir } => {
+            let layers = layer::parse_layers(&layers)?;
+            let layer_fses = layers
+                .iter()
+                .map(|l| {
+                    let key = l.resolved_key()?;
+                    let device = open_device(&l.path, l.protect_integrity, key)?;
+                    Ok(sefs::SEFS::open(device, &StdTimeProvider, &StdUuidProvider)?)
+                })
+                .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+
             let mnt_dir = dir.clone();
             // Ctrl-C handler
             ctrlc::set_handler(move || {
@@ -122,22 +219,13 @@ fn main() -> Result<(), Box<dyn Error>> {
                     }
                 }
             })?;
-            // Mount as an UnionFS
-            if container.is_dir() {
-                let union_fs = {
-                    let device = sgx_dev::SgxStorage::new(
-                        enclave.geteid(),
-                        &container,
-                        sgx_dev::EncryptMode::EncryptAutoKey,
-                    );
-                    let container_fs =
-                        sefs::SEFS::open(Box::new(device), &StdTimeProvider, &StdUuidProvider)?;
-                    unionfs::UnionFS::new(vec![container_fs, image_fs])?
-                };
+            // A single layer mounts directly as an SEFS; several layers are
+            // composed into a UnionFS, writable top layer first.
+            if layer_fses.len() > 1 {
+                let union_fs = unionfs::UnionFS::new(layer_fses)?;
                 fuse::mount(VfsFuse::new(union_fs), &dir, &[])?;
             } else {
-                // Mount as an SEFS
-                fuse::mount(VfsFuse::new(image_fs), &dir, &[])?;
+                fuse::mount(VfsFuse::new(layer_fses.into_iter().next().unwrap()), &dir, &[])?;
             }
         }
         Cmd::Zip {
@@ -145,14 +233,19 @@ fn main() -> Result<(), Box<dyn Error>> {
             image,
             protect_integrity,
             key,
+            passphrase,
         } => {
             let sefs_fs = {
                 std::fs::create_dir(&image)?;
-                let mode = sgx_dev::EncryptMode::from_parameters(protect_integrity, key)?;
-                let device = sgx_dev::SgxStorage::new(enclave.geteid(), &image, mode);
-                sefs::SEFS::create(Box::new(device), &StdTimeProvider, &StdUuidProvider)?
+                let key = match passphrase {
+                    Some(passphrase) => Some(keyfile::key_to_hex_dashed(&keyfile::create(&image, &passphrase)?)),
+                    None => key,
+                };
+                let device = open_device(&image, protect_integrity, key)?;
+                sefs::SEFS::create(device, &StdTimeProvider, &StdUuidProvider)?
             };
             zip_dir(&dir, sefs_fs.root_inode())?;
+            metadata::capture_into(&dir, &sefs_fs.root_inode())?;
             println!("Encrypt the SEFS image successfully");
             if protect_integrity {
                 let root_mac_str = {
@@ -173,16 +266,63 @@ fn main() -> Result<(), Box<dyn Error>> {
             dir,
             protect_integrity,
             key,
+            passphrase,
         } => {
             let sefs_fs = {
-                let mode = sgx_dev::EncryptMode::from_parameters(protect_integrity, key)?;
-                let device = sgx_dev::SgxStorage::new(enclave.geteid(), &image, mode);
-                sefs::SEFS::open(Box::new(device), &StdTimeProvider, &StdUuidProvider)?
+                let key = match passphrase {
+                    Some(passphrase) => Some(keyfile::key_to_hex_dashed(&keyfile::open(&image, &passphrase)?)),
+                    None => key,
+                };
+                let device = open_device(&image, protect_integrity, key)?;
+                sefs::SEFS::open(device, &StdTimeProvider, &StdUuidProvider)?
             };
             std::fs::create_dir(&dir)?;
             unzip_dir(&dir, sefs_fs.root_inode())?;
+            metadata::restore_from(&sefs_fs.root_inode(), &dir)?;
             println!("Decrypt the SEFS image successfully");
         }
+        Cmd::Fsck {
+            image,
+            protect_integrity,
+            key,
+            repair,
+        } => {
+            let sefs_fs = {
+                let device = open_device(&image, protect_integrity, key)?;
+                sefs::SEFS::open(device, &StdTimeProvider, &StdUuidProvider)?
+            };
+            let clean = fsck::fsck_image(&sefs_fs, &image, repair)?;
+            if !clean {
+                exit(1);
+            }
+        }
+        Cmd::CreateGolden {
+            image,
+            protect_integrity,
+            key,
+            out,
+        } => {
+            let sefs_fs = {
+                let device = open_device(&image, protect_integrity, key)?;
+                sefs::SEFS::open(device, &StdTimeProvider, &StdUuidProvider)?
+            };
+            golden::create_golden(&sefs_fs.root_inode(), &out)?;
+            println!("Wrote golden manifest to {:?}", out);
+        }
+        Cmd::CheckGolden {
+            image,
+            protect_integrity,
+            key,
+            golden,
+        } => {
+            let sefs_fs = {
+                let device = open_device(&image, protect_integrity, key)?;
+                sefs::SEFS::open(device, &StdTimeProvider, &StdUuidProvider)?
+            };
+            if !golden::check_golden(&sefs_fs.root_inode(), &golden)? {
+                exit(1);
+            }
+        }
     }
     Ok(())
 }
@@ -0,0 +1,351 @@
+use std::error::Error;
+use std::fs;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use nix::sys::stat::{major, minor, mknod, mode_t, Mode, SFlag};
+use nix::unistd::mkfifo;
+
+use rcore_fs::vfs::{FileType, INode};
+
+use crate::keyfile::{hex_decode, hex_encode};
+
+/// Name of the regular file written inside the *encrypted* SEFS tree (next
+/// to the zipped content, as a sibling of root's other entries) that records
+/// the UNIX metadata `zip_dir`/`unzip_dir` cannot round-trip themselves:
+/// symlink targets, extended attributes, and device/FIFO/socket nodes.
+/// `zip_dir`/`unzip_dir` and `VfsFuse` live in the `rcore_fs_cli` crate,
+/// whose source is not part of this checkout, so this walks the source/
+/// target directory directly rather than extending the SEFS inode format
+/// itself — it covers the `zip`/`unzip` round-trip, not a live mount. Living
+/// inside the tree (rather than as a plaintext file beside the image
+/// directory) means it is encrypted, and MAC'd, exactly like everything else
+/// in the image.
+const METADATA_SIDECAR: &str = ".sefs-metadata.sidecar";
+
+/// One path's symlink target, xattrs, or special-file type, as captured from
+/// (or to be replayed onto) a plain host directory.
+pub struct Entry {
+    path: String,
+    symlink_target: Option<String>,
+    xattrs: Vec<(String, Vec<u8>)>,
+    special: Option<SpecialNode>,
+}
+
+/// A device/FIFO/socket node, recorded by `stat(2)`'s view of it.
+struct SpecialNode {
+    kind: SpecialKind,
+    mode: mode_t,
+    major: u64,
+    minor: u64,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SpecialKind {
+    BlockDevice,
+    CharDevice,
+    Fifo,
+    Socket,
+}
+
+impl SpecialKind {
+    fn tag(self) -> &'static str {
+        match self {
+            SpecialKind::BlockDevice => "block",
+            SpecialKind::CharDevice => "char",
+            SpecialKind::Fifo => "fifo",
+            SpecialKind::Socket => "socket",
+        }
+    }
+
+    fn from_tag(tag: &str) -> Option<SpecialKind> {
+        match tag {
+            "block" => Some(SpecialKind::BlockDevice),
+            "char" => Some(SpecialKind::CharDevice),
+            "fifo" => Some(SpecialKind::Fifo),
+            "socket" => Some(SpecialKind::Socket),
+            _ => None,
+        }
+    }
+}
+
+/// Walk `dir`, recording every symlink target, xattr, and special-file node
+/// found under it, relative to `dir` itself.
+pub fn capture(dir: &Path) -> Result<Vec<Entry>, Box<dyn Error>> {
+    let mut entries = Vec::new();
+    walk(dir, &PathBuf::new(), &mut entries)?;
+    Ok(entries)
+}
+
+fn walk(root: &Path, rel: &Path, entries: &mut Vec<Entry>) -> Result<(), Box<dyn Error>> {
+    let abs = root.join(rel);
+    let metadata = fs::symlink_metadata(&abs)?;
+    let file_type = metadata.file_type();
+
+    let symlink_target = if file_type.is_symlink() {
+        Some(fs::read_link(&abs)?.to_string_lossy().into_owned())
+    } else {
+        None
+    };
+    let special = special_node(&metadata);
+    let xattrs = if file_type.is_symlink() { Vec::new() } else { read_xattrs(&abs)? };
+
+    if symlink_target.is_some() || special.is_some() || !xattrs.is_empty() {
+        entries.push(Entry {
+            path: rel.to_string_lossy().into_owned(),
+            symlink_target,
+            xattrs,
+            special,
+        });
+    }
+
+    if file_type.is_dir() {
+        for child in fs::read_dir(&abs)? {
+            let child = child?;
+            walk(root, &rel.join(child.file_name()), entries)?;
+        }
+    }
+    Ok(())
+}
+
+fn special_node(metadata: &fs::Metadata) -> Option<SpecialNode> {
+    let file_type = metadata.file_type();
+    let kind = if file_type.is_block_device() {
+        SpecialKind::BlockDevice
+    } else if file_type.is_char_device() {
+        SpecialKind::CharDevice
+    } else if file_type.is_fifo() {
+        SpecialKind::Fifo
+    } else if file_type.is_socket() {
+        SpecialKind::Socket
+    } else {
+        return None;
+    };
+    let rdev = metadata.rdev();
+    Some(SpecialNode { kind, mode: metadata.mode() as mode_t, major: major(rdev), minor: minor(rdev) })
+}
+
+fn read_xattrs(path: &Path) -> Result<Vec<(String, Vec<u8>)>, Box<dyn Error>> {
+    let mut out = Vec::new();
+    for name in xattr::list(path)? {
+        let name = name.to_string_lossy().into_owned();
+        if let Some(value) = xattr::get(path, &name)? {
+            out.push((name, value));
+        }
+    }
+    Ok(out)
+}
+
+/// Serialize `entries` to the tab-separated sidecar format, one line per
+/// symlink target, xattr, or special node (a path with several xattrs gets
+/// several `XATTR` lines). Every free-form field (`path` itself, symlink
+/// targets, xattr names and values) is hex-encoded; a `SPECIAL` line's tag,
+/// octal mode and decimal major/minor are written as they are. POSIX
+/// filenames may legally contain a tab or newline, which would otherwise
+/// shift the field count when `parse_line` splits on `\t`.
+fn render(entries: &[Entry]) -> String {
+    let mut out = String::new();
+    for entry in entries {
+        let path_hex = hex_encode(entry.path.as_bytes());
+        if let Some(target) = &entry.symlink_target {
+            out += &format!("SYMLINK\t{}\t{}\n", path_hex, hex_encode(target.as_bytes()));
+        }
+        if let Some(special) = &entry.special {
+            out += &format!(
+                "SPECIAL\t{}\t{}\t{:o}\t{}\t{}\n",
+                path_hex,
+                special.kind.tag(),
+                special.mode,
+                special.major,
+                special.minor
+            );
+        }
+        for (name, value) in &entry.xattrs {
+            out += &format!("XATTR\t{}\t{}\t{}\n", path_hex, hex_encode(name.as_bytes()), hex_encode(value));
+        }
+    }
+    out
+}
+
+/// One parsed sidecar line, ready to replay onto a directory.
+enum ParsedLine {
+    Symlink { rel: String, target: String },
+    Xattr { rel: String, name: String, value: Vec<u8> },
+    Special { rel: String, kind: SpecialKind, mode: mode_t, major: u64, minor: u64 },
+}
+
+/// Parse one sidecar line. A malformed line (bad hex, wrong field count,
+/// unrecognized tag) is reported as an error for that line alone — callers
+/// skip it and keep going, rather than aborting the whole sidecar the way an
+/// un-hex-encoded path containing a tab or newline once did.
+fn parse_line(line: &str) -> Result<ParsedLine, Box<dyn Error>> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    match fields.as_slice() {
+        ["SYMLINK", rel_hex, target_hex] => Ok(ParsedLine::Symlink {
+            rel: String::from_utf8(hex_decode(rel_hex)?)?,
+            target: String::from_utf8(hex_decode(target_hex)?)?,
+        }),
+        ["XATTR", rel_hex, name_hex, value_hex] => Ok(ParsedLine::Xattr {
+            rel: String::from_utf8(hex_decode(rel_hex)?)?,
+            name: String::from_utf8(hex_decode(name_hex)?)?,
+            value: hex_decode(value_hex)?,
+        }),
+        ["SPECIAL", rel_hex, tag, mode_octal, major_s, minor_s] => Ok(ParsedLine::Special {
+            rel: String::from_utf8(hex_decode(rel_hex)?)?,
+            kind: SpecialKind::from_tag(tag).ok_or_else(|| format!("unrecognized special-node tag {:?}", tag))?,
+            mode: mode_t::from_str_radix(mode_octal, 8)?,
+            major: major_s.parse()?,
+            minor: minor_s.parse()?,
+        }),
+        _ => Err(format!("unrecognized metadata sidecar line: {:?}", line).into()),
+    }
+}
+
+/// Find the root-level sidecar child inode, if one was written by `zip`.
+fn find_sidecar(root: &Arc<dyn INode>) -> Option<Arc<dyn INode>> {
+    root.find(METADATA_SIDECAR).ok()
+}
+
+/// Read an inode's full content.
+fn read_inode(inode: &Arc<dyn INode>) -> Result<Vec<u8>, Box<dyn Error>> {
+    let size = inode.metadata()?.size;
+    let mut buf = vec![0u8; size];
+    let mut offset = 0;
+    while offset < size {
+        let n = inode.read_at(offset, &mut buf[offset..])?;
+        if n == 0 {
+            break;
+        }
+        offset += n;
+    }
+    Ok(buf)
+}
+
+/// Capture `dir`'s extra metadata and, if any was found, write it as a
+/// regular file named [`METADATA_SIDECAR`] under `root` — inside the
+/// encrypted tree, so it gets the same confidentiality and integrity
+/// protection as every other file in the image. A no-op for plain trees
+/// without symlinks, xattrs, or special files, so they zip as before.
+pub fn capture_into(dir: &Path, root: &Arc<dyn INode>) -> Result<(), Box<dyn Error>> {
+    let entries = capture(dir)?;
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let record = render(&entries);
+    let inode = root.create(METADATA_SIDECAR, FileType::File, 0o600)?;
+    inode.write_at(0, record.as_bytes())?;
+    Ok(())
+}
+
+/// Replay the sidecar under `root`, if any, onto `dir`: recreate symlinks,
+/// restore xattrs, and attempt to recreate device/FIFO/socket nodes via
+/// `mknod(2)`/`mkfifo(2)` (which silently requires privileges `unzip_dir`'s
+/// caller may not have — failures there are reported, not fatal, since the
+/// regular files/dirs `unzip_dir` already wrote are still usable without
+/// them). `unzip_dir` has already extracted the sidecar itself as an
+/// ordinary file at `dir`'s root (it doesn't know to skip it); that copy is
+/// removed afterwards since it is an internal artifact, not part of the
+/// original tree.
+pub fn restore_from(root: &Arc<dyn INode>, dir: &Path) -> Result<(), Box<dyn Error>> {
+    let sidecar = match find_sidecar(root) {
+        Some(inode) => inode,
+        None => return Ok(()),
+    };
+    let record = String::from_utf8(read_inode(&sidecar)?)?;
+    for line in record.lines() {
+        if let Err(e) = apply_line(line, dir) {
+            println!("unzip: skipping unreadable {} line ({}): {:?}", METADATA_SIDECAR, e, line);
+        }
+    }
+    let _ = fs::remove_file(dir.join(METADATA_SIDECAR));
+    Ok(())
+}
+
+fn apply_line(line: &str, dir: &Path) -> Result<(), Box<dyn Error>> {
+    match parse_line(line)? {
+        ParsedLine::Symlink { rel, target } => {
+            std::os::unix::fs::symlink(&target, dir.join(&rel))?;
+        }
+        ParsedLine::Xattr { rel, name, value } => {
+            xattr::set(dir.join(&rel), &name, &value)?;
+        }
+        ParsedLine::Special { rel, kind, mode, major, minor } => {
+            let path = dir.join(&rel);
+            let result = match kind {
+                SpecialKind::Fifo => mkfifo(&path, Mode::from_bits_truncate(mode)).map_err(nix::Error::from),
+                SpecialKind::BlockDevice | SpecialKind::CharDevice => {
+                    let sflag = if kind == SpecialKind::BlockDevice { SFlag::S_IFBLK } else { SFlag::S_IFCHR };
+                    let dev = nix::sys::stat::makedev(major, minor);
+                    mknod(&path, sflag, Mode::from_bits_truncate(mode), dev).map_err(nix::Error::from)
+                }
+                SpecialKind::Socket => {
+                    mknod(&path, SFlag::S_IFSOCK, Mode::from_bits_truncate(mode), 0).map_err(nix::Error::from)
+                }
+            };
+            if let Err(e) = result {
+                println!("unzip: could not recreate {} node {:?} ({}); skipping", kind.tag(), rel, e);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_parse_round_trip() {
+        let entries = vec![Entry {
+            path: "a/b".to_string(),
+            symlink_target: Some("../target".to_string()),
+            xattrs: vec![("user.note".to_string(), b"hello".to_vec())],
+            special: None,
+        }];
+        let record = render(&entries);
+        let mut saw_symlink = false;
+        let mut saw_xattr = false;
+        for line in record.lines() {
+            match parse_line(line).unwrap() {
+                ParsedLine::Symlink { rel, target } => {
+                    assert_eq!(rel, "a/b");
+                    assert_eq!(target, "../target");
+                    saw_symlink = true;
+                }
+                ParsedLine::Xattr { rel, name, value } => {
+                    assert_eq!(rel, "a/b");
+                    assert_eq!(name, "user.note");
+                    assert_eq!(value, b"hello");
+                    saw_xattr = true;
+                }
+                ParsedLine::Special { .. } => panic!("unexpected SPECIAL line"),
+            }
+        }
+        assert!(saw_symlink && saw_xattr);
+    }
+
+    #[test]
+    fn path_with_tab_and_newline_round_trips() {
+        // POSIX filenames may legally contain a tab or newline; since `path`
+        // is hex-encoded, it must not shift the field count on parse.
+        let entries = vec![Entry {
+            path: "weird\tname\nwith-newline".to_string(),
+            symlink_target: Some("target".to_string()),
+            xattrs: Vec::new(),
+            special: None,
+        }];
+        let record = render(&entries);
+        assert_eq!(record.lines().count(), 1);
+        match parse_line(record.lines().next().unwrap()).unwrap() {
+            ParsedLine::Symlink { rel, .. } => assert_eq!(rel, "weird\tname\nwith-newline"),
+            _ => panic!("expected SYMLINK line"),
+        }
+    }
+
+    #[test]
+    fn malformed_line_is_reported_not_fatal() {
+        assert!(parse_line("SYMLINK\tnotvalidhex").is_err());
+        assert!(parse_line("BOGUS\tfoo\tbar").is_err());
+    }
+}
@@ -0,0 +1,255 @@
+use std::fs;
+use std::fs::File as StdFile;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use aes_gcm::aead::{Aead, NewAead};
+use aes_gcm::{Aes128Gcm, Key, Nonce};
+use rand::RngCore;
+
+// `rcore_fs_sefs::dev::{Storage, File}` is the per-inode-file storage trait
+// pair `sgx_dev::SgxStorage` implements: one sealed host file per SEFS inode,
+// under the image directory, named by decimal `file_id` (this is exactly
+// what `fsck::find_orphans` scans for). That crate's source is not part of
+// this checkout, so the exact trait signatures below are inferred from that
+// usage rather than read off the source.
+use rcore_fs_sefs::dev::{File as SefsFile, Storage};
+
+use crate::backend::CryptoBackend;
+
+/// Plaintext block size. Blocks are encrypted and MAC'd independently, so
+/// each inode file stores `BLOCK_SIZE`-byte ciphertext slots with a trailing
+/// 16-byte MAC, rather than one contiguous ciphertext.
+const BLOCK_SIZE: usize = 4096;
+const MAC_SIZE: usize = 16;
+const SLOT_SIZE: usize = BLOCK_SIZE + MAC_SIZE;
+
+/// Plaintext record holding a randomly generated `EncryptAutoKey` key. Not a
+/// valid decimal `file_id`, so `fsck::find_orphans`'s scan ignores it. There
+/// is no enclave to seal it against, so — unlike the enclave backend, which
+/// never writes the auto key to disk at all — the software backend's auto
+/// key is only as protected as this file's filesystem permissions.
+const AUTO_KEY_FILE: &str = "autokey.meta";
+
+/// Pure-Rust AES-128-GCM implementation of [`CryptoBackend`], used in place
+/// of `sgx_dev::SgxStorage` when no `--enclave` is given. Produces images
+/// byte-compatible with the enclave backend for the same key, so `Zip`,
+/// `Unzip`, `Mount` and `Fsck` work unmodified against either backend.
+pub struct SoftCryptoBackend;
+
+impl CryptoBackend for SoftCryptoBackend {
+    fn encrypt_block(&self, key: &[u8; 16], block_index: u64, block: &mut [u8]) -> [u8; 16] {
+        let cipher = Aes128Gcm::new(Key::from_slice(key));
+        let sealed = cipher
+            .encrypt(Nonce::from_slice(&block_nonce(block_index)), block.as_ref())
+            .expect("AES-128-GCM encryption cannot fail for valid inputs");
+        let (ciphertext, mac) = sealed.split_at(sealed.len() - MAC_SIZE);
+        block.copy_from_slice(ciphertext);
+        let mut mac_arr = [0u8; MAC_SIZE];
+        mac_arr.copy_from_slice(mac);
+        mac_arr
+    }
+
+    fn decrypt_block(&self, key: &[u8; 16], block_index: u64, block: &mut [u8], mac: &[u8; 16]) -> bool {
+        let cipher = Aes128Gcm::new(Key::from_slice(key));
+        let mut sealed = Vec::with_capacity(block.len() + MAC_SIZE);
+        sealed.extend_from_slice(block);
+        sealed.extend_from_slice(mac);
+        match cipher.decrypt(Nonce::from_slice(&block_nonce(block_index)), sealed.as_ref()) {
+            Ok(plaintext) => {
+                block.copy_from_slice(&plaintext);
+                true
+            }
+            // Leave `block` untouched on failure — callers must treat a
+            // `false` return as "this content is not trustworthy" and never
+            // read `block` afterwards, rather than falling back to it.
+            Err(_) => false,
+        }
+    }
+
+    fn derive_auto_key(&self) -> [u8; 16] {
+        let mut key = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut key);
+        key
+    }
+}
+
+/// A 96-bit GCM nonce unique to `block_index` within one inode file: the low
+/// 8 bytes carry the index, the top 4 stay zero. A key is only ever used
+/// within a single image (`EncryptAutoKey` draws a fresh one per image), and
+/// distinct inode files never share a block index the way distinct blocks of
+/// the same file would, so this guarantees the nonce is never reused under
+/// the same key.
+fn block_nonce(block_index: u64) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..8].copy_from_slice(&block_index.to_le_bytes());
+    nonce
+}
+
+/// Parse the `xx-xx-...-xx` dashed-hex form accepted by `--key`.
+fn parse_hex_key(s: &str) -> io::Result<[u8; 16]> {
+    let bytes: Result<Vec<u8>, _> = s.split('-').map(|b| u8::from_str_radix(b, 16)).collect();
+    let bytes = bytes.map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "malformed --key"))?;
+    if bytes.len() != 16 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "--key must be 16 bytes"));
+    }
+    let mut key = [0u8; 16];
+    key.copy_from_slice(&bytes);
+    Ok(key)
+}
+
+/// Load the auto key persisted at `image/autokey.meta`, generating and
+/// persisting a fresh one the first time the image is created.
+fn load_or_create_auto_key(image: &Path, backend: &SoftCryptoBackend) -> io::Result<[u8; 16]> {
+    let path = image.join(AUTO_KEY_FILE);
+    if let Ok(hex) = fs::read_to_string(&path) {
+        return parse_hex_key(hex.trim());
+    }
+    let key = backend.derive_auto_key();
+    let hex = key.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join("-");
+    fs::write(&path, hex)?;
+    Ok(key)
+}
+
+/// Drop-in substitute for `sgx_dev::SgxStorage` that performs block crypto
+/// in pure Rust instead of inside an SGX enclave. Implements `Storage` the
+/// same way: one host file per SEFS inode, named `<image>/<file_id>`, so
+/// `fsck`'s orphan scan (which looks for image-directory entries whose name
+/// parses as a `file_id`) sees real candidates under this backend too.
+pub struct SoftStorage {
+    image: PathBuf,
+    key: [u8; 16],
+}
+
+impl SoftStorage {
+    /// Open the existing image directory `image`, taking the same
+    /// `(protect_integrity, key)` pair `EncryptMode::from_parameters` does:
+    /// an explicit dashed-hex key, or `None` to auto-generate one. The
+    /// directory is not created here. `protect_integrity` is accepted but
+    /// does not change anything: this backend has no integrity-only mode,
+    /// and every block is always AES-GCM encrypted, with its AEAD tag checked
+    /// on every read, since a failed check means the bytes are not the
+    /// plaintext that was written.
+    pub fn new(image: &Path, _protect_integrity: bool, key: Option<String>) -> io::Result<Self> {
+        let backend = SoftCryptoBackend;
+        let key = match key {
+            Some(hex) => parse_hex_key(&hex)?,
+            None => load_or_create_auto_key(image, &backend)?,
+        };
+        Ok(SoftStorage { image: image.to_path_buf(), key })
+    }
+
+    fn file_path(&self, file_id: usize) -> PathBuf {
+        self.image.join(file_id.to_string())
+    }
+}
+
+impl Storage for SoftStorage {
+    fn open(&self, file_id: usize) -> io::Result<Box<dyn SefsFile>> {
+        let file = fs::OpenOptions::new().read(true).write(true).open(self.file_path(file_id))?;
+        Ok(Box::new(SoftFile { file: Mutex::new(file), key: self.key }))
+    }
+
+    fn create(&self, file_id: usize) -> io::Result<Box<dyn SefsFile>> {
+        let file = fs::OpenOptions::new().read(true).write(true).create(true).open(self.file_path(file_id))?;
+        Ok(Box::new(SoftFile { file: Mutex::new(file), key: self.key }))
+    }
+
+    fn remove(&self, file_id: usize) -> io::Result<()> {
+        fs::remove_file(self.file_path(file_id))
+    }
+}
+
+/// One inode's on-disk file under the software backend, encrypting/decrypting
+/// its `BLOCK_SIZE`-byte blocks (plus trailing MAC) through `SoftCryptoBackend`.
+struct SoftFile {
+    file: Mutex<StdFile>,
+    key: [u8; 16],
+}
+
+impl SoftFile {
+    fn read_block(&self, block_index: u64, out: &mut [u8; BLOCK_SIZE]) -> io::Result<()> {
+        use std::io::{Read, Seek, SeekFrom};
+        let mut slot = [0u8; SLOT_SIZE];
+        let mut file = self.file.lock().unwrap();
+        file.seek(SeekFrom::Start(block_index * SLOT_SIZE as u64))?;
+        match file.read_exact(&mut slot) {
+            Ok(()) => {}
+            // A block never written yet reads as all zeros, matching a
+            // sparse/newly-created file.
+            Err(_) => {
+                out.fill(0);
+                return Ok(());
+            }
+        }
+        let (ciphertext, mac) = slot.split_at(BLOCK_SIZE);
+        let mut mac_arr = [0u8; MAC_SIZE];
+        mac_arr.copy_from_slice(mac);
+        out.copy_from_slice(ciphertext);
+        // AEAD tag verification failing means this is not the plaintext that
+        // was written — wrong key, bit-rot, or tampering — and must never be
+        // handed to the caller as though it were. `protect_integrity` has no
+        // effect on this backend: ciphertext authenticity is always enforced.
+        let backend = SoftCryptoBackend;
+        if !backend.decrypt_block(&self.key, block_index, out, &mac_arr) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "block failed AEAD authentication"));
+        }
+        Ok(())
+    }
+
+    fn write_block(&self, block_index: u64, block: &[u8; BLOCK_SIZE]) -> io::Result<()> {
+        use std::io::{Seek, SeekFrom, Write};
+        let backend = SoftCryptoBackend;
+        let mut plaintext = *block;
+        let mac = backend.encrypt_block(&self.key, block_index, &mut plaintext);
+        let mut slot = [0u8; SLOT_SIZE];
+        slot[..BLOCK_SIZE].copy_from_slice(&plaintext);
+        slot[BLOCK_SIZE..].copy_from_slice(&mac);
+        let mut file = self.file.lock().unwrap();
+        file.seek(SeekFrom::Start(block_index * SLOT_SIZE as u64))?;
+        file.write_all(&slot)
+    }
+}
+
+impl SefsFile for SoftFile {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> io::Result<usize> {
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset + done;
+            let block_index = (pos / BLOCK_SIZE) as u64;
+            let block_off = pos % BLOCK_SIZE;
+            let mut block = [0u8; BLOCK_SIZE];
+            self.read_block(block_index, &mut block)?;
+            let n = (BLOCK_SIZE - block_off).min(buf.len() - done);
+            buf[done..done + n].copy_from_slice(&block[block_off..block_off + n]);
+            done += n;
+        }
+        Ok(done)
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> io::Result<usize> {
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset + done;
+            let block_index = (pos / BLOCK_SIZE) as u64;
+            let block_off = pos % BLOCK_SIZE;
+            let mut block = [0u8; BLOCK_SIZE];
+            self.read_block(block_index, &mut block)?;
+            let n = (BLOCK_SIZE - block_off).min(buf.len() - done);
+            block[block_off..block_off + n].copy_from_slice(&buf[done..done + n]);
+            self.write_block(block_index, &block)?;
+            done += n;
+        }
+        Ok(done)
+    }
+
+    fn set_len(&self, len: usize) -> io::Result<()> {
+        let slots = (len + BLOCK_SIZE - 1) / BLOCK_SIZE;
+        self.file.lock().unwrap().set_len((slots * SLOT_SIZE) as u64)
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.file.lock().unwrap().sync_data()
+    }
+}